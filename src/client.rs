@@ -0,0 +1,112 @@
+//! Reverse-tunnel client: dials the server, authenticates with the shared
+//! secret, and forwards each requested public connection to a local service.
+
+use std::net::SocketAddr;
+
+use anyhow::{bail, Context};
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::control::{answer_challenge, ClientMessage, ServerMessage, Transport};
+use crate::{connect_happy_eyeballs, Target, TargetResolver};
+use std::time::Duration;
+
+/// Run the reverse-tunnel client until the control connection drops.
+pub async fn run(
+    server_addr: SocketAddr,
+    local: Target,
+    secret: String,
+    resolver: TargetResolver,
+    connect_timeout: Duration,
+    happy_eyeballs_delay: Duration,
+) -> anyhow::Result<()> {
+    let socket = TcpStream::connect(server_addr)
+        .await
+        .context("connecting to reverse-tunnel server")?;
+    let mut control = Transport::new(socket);
+
+    let challenge = match control.expect::<ServerMessage>().await? {
+        ServerMessage::Challenge(uuid) => uuid,
+        other => bail!("expected challenge, got {other:?}"),
+    };
+    control
+        .send(&ClientMessage::Authenticate(answer_challenge(
+            &secret, &challenge,
+        )))
+        .await?;
+    control.send(&ClientMessage::Hello(0)).await?;
+
+    match control.expect::<ServerMessage>().await? {
+        ServerMessage::Hello(port) => {
+            info!(server = %server_addr, local = %local, public_port = port, "tunnel established")
+        }
+        ServerMessage::Error(msg) => bail!("server rejected tunnel: {msg}"),
+        other => bail!("expected hello, got {other:?}"),
+    }
+
+    loop {
+        match control.recv::<ServerMessage>().await? {
+            Some(ServerMessage::Connection(uuid)) => {
+                let local = local.clone();
+                let resolver = resolver.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = handle_data(
+                        server_addr,
+                        uuid,
+                        local,
+                        resolver,
+                        connect_timeout,
+                        happy_eyeballs_delay,
+                    )
+                    .await
+                    {
+                        warn!(uuid = %uuid, "data connection error: {err}");
+                    }
+                });
+            }
+            Some(ServerMessage::Heartbeat) => {}
+            Some(ServerMessage::Error(msg)) => bail!("server error: {msg}"),
+            Some(other) => warn!("unexpected control message: {other:?}"),
+            None => {
+                info!("control connection closed by server");
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Open a fresh data stream to the server for `uuid`, bridge it to the local
+/// service, and splice the two together.
+async fn handle_data(
+    server_addr: SocketAddr,
+    uuid: Uuid,
+    local: Target,
+    resolver: TargetResolver,
+    connect_timeout: Duration,
+    happy_eyeballs_delay: Duration,
+) -> anyhow::Result<()> {
+    let socket = TcpStream::connect(server_addr)
+        .await
+        .context("opening data connection to server")?;
+    let mut data = Transport::new(socket);
+
+    // The server greets every connection with a challenge; consume it so it
+    // doesn't leak into the spliced byte stream. The data connection is
+    // authorized by the unguessable `uuid` rather than a second handshake.
+    match data.expect::<ServerMessage>().await? {
+        ServerMessage::Challenge(_) => {}
+        other => bail!("expected challenge on data connection, got {other:?}"),
+    }
+    data.send(&ClientMessage::Accept(uuid)).await?;
+
+    let addrs = resolver.resolve(&local).await?;
+    let (mut local_socket, _) =
+        connect_happy_eyeballs(addrs, connect_timeout, happy_eyeballs_delay).await?;
+
+    let mut data = data.into_data_stream();
+    let (to_local, to_remote) =
+        tokio::io::copy_bidirectional(&mut data, &mut local_socket).await?;
+    info!(uuid = %uuid, public_to_local = to_local, local_to_public = to_remote, "closed tunnel connection");
+    Ok(())
+}
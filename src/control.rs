@@ -0,0 +1,160 @@
+//! Shared control-channel protocol for the reverse-tunnel modes.
+//!
+//! Control and data connections both speak newline-delimited JSON framed by
+//! [`Transport`]. The server drives a challenge/response handshake on control
+//! connections; data connections carry a single [`ClientMessage::Accept`] with
+//! the UUID the server handed out, after which raw bytes are spliced.
+
+use anyhow::{bail, Context};
+use hmac::{Hmac, Mac};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::io::Cursor;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, Chain, Join};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use uuid::Uuid;
+
+/// Messages sent by the client over the control and data channels.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ClientMessage {
+    /// HMAC-SHA256 of the server's challenge, hex-encoded, keyed by the secret.
+    Authenticate(String),
+    /// Request to expose a service. The server does not use the port value: it
+    /// always exposes its own configured public address.
+    Hello(u16),
+    /// Claim a pending public connection on a freshly opened data stream.
+    Accept(Uuid),
+}
+
+/// Messages sent by the server over the control channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ServerMessage {
+    /// Random challenge the client must authenticate against.
+    Challenge(Uuid),
+    /// Confirms the public port the service is exposed on.
+    Hello(u16),
+    /// Keeps the control connection (and any NAT mapping) alive.
+    Heartbeat,
+    /// Asks the client to open a data stream for an incoming public connection.
+    Connection(Uuid),
+    /// Terminal protocol error; the peer should disconnect.
+    Error(String),
+}
+
+/// A data stream recovered from a [`Transport`], with any bytes buffered past
+/// the framed handshake chained ahead of the live socket so nothing is lost.
+pub type DataStream = Join<Chain<Cursor<Vec<u8>>, OwnedReadHalf>, OwnedWriteHalf>;
+
+/// Newline-delimited JSON transport over a split [`TcpStream`].
+pub struct Transport {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+    line: String,
+}
+
+impl Transport {
+    pub fn new(stream: TcpStream) -> Self {
+        let (read, write) = stream.into_split();
+        Self {
+            reader: BufReader::new(read),
+            writer: write,
+            line: String::new(),
+        }
+    }
+
+    /// Serialize `msg` as a single JSON line and flush it.
+    pub async fn send<T: Serialize>(&mut self, msg: &T) -> anyhow::Result<()> {
+        let mut encoded = serde_json::to_string(msg).context("serializing control message")?;
+        encoded.push('\n');
+        self.writer
+            .write_all(encoded.as_bytes())
+            .await
+            .context("writing control message")?;
+        self.writer.flush().await.context("flushing control message")?;
+        Ok(())
+    }
+
+    /// Read one JSON line, returning `None` on a clean end-of-stream.
+    pub async fn recv<T: DeserializeOwned>(&mut self) -> anyhow::Result<Option<T>> {
+        self.line.clear();
+        let n = self
+            .reader
+            .read_line(&mut self.line)
+            .await
+            .context("reading control message")?;
+        if n == 0 {
+            return Ok(None);
+        }
+        let msg = serde_json::from_str(self.line.trim_end())
+            .context("deserializing control message")?;
+        Ok(Some(msg))
+    }
+
+    /// Like [`recv`](Self::recv) but treats a dropped connection as an error.
+    pub async fn expect<T: DeserializeOwned>(&mut self) -> anyhow::Result<T> {
+        self.recv()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("connection closed before expected message"))
+    }
+
+    /// Consume the transport and hand back a duplex stream for splicing. Any
+    /// bytes the [`BufReader`] read past the last framed line are preserved.
+    pub fn into_data_stream(self) -> DataStream {
+        let leftover = self.reader.buffer().to_vec();
+        let read = self.reader.into_inner();
+        tokio::io::join(Cursor::new(leftover).chain(read), self.writer)
+    }
+}
+
+/// Compute the hex HMAC-SHA256 response for `challenge` under `secret`.
+pub fn answer_challenge(secret: &str, challenge: &Uuid) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(challenge.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Verify a client's `response` to `challenge` under `secret` in constant time.
+pub fn verify_challenge(secret: &str, challenge: &Uuid, response: &str) -> anyhow::Result<()> {
+    let expected =
+        hex::decode(response).context("authentication response is not valid hex")?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(challenge.as_bytes());
+    if mac.verify_slice(&expected).is_err() {
+        bail!("authentication failed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answer_verifies_against_same_secret() {
+        let challenge = Uuid::from_u128(0x0123456789abcdef0123456789abcdef);
+        let response = answer_challenge("s3cret", &challenge);
+        assert!(verify_challenge("s3cret", &challenge, &response).is_ok());
+    }
+
+    #[test]
+    fn verify_rejects_wrong_secret() {
+        let challenge = Uuid::from_u128(1);
+        let response = answer_challenge("right", &challenge);
+        assert!(verify_challenge("wrong", &challenge, &response).is_err());
+    }
+
+    #[test]
+    fn verify_rejects_wrong_challenge() {
+        let response = answer_challenge("s3cret", &Uuid::from_u128(1));
+        assert!(verify_challenge("s3cret", &Uuid::from_u128(2), &response).is_err());
+    }
+
+    #[test]
+    fn verify_rejects_non_hex_response() {
+        assert!(verify_challenge("s3cret", &Uuid::from_u128(1), "not-hex").is_err());
+    }
+}
@@ -1,24 +1,41 @@
-use anyhow::Context;
+use anyhow::{bail, Context};
 use clap::Parser;
-use std::net::SocketAddr;
+use futures::stream::{FuturesUnordered, StreamExt};
+use hickory_resolver::TokioAsyncResolver;
+use rand::Rng;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::signal;
-use tokio::time;
+use tokio::sync::Mutex;
+use tokio::time::{self, Instant};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
 use tracing::Instrument;
 use tracing::{info, warn};
 use tracing_subscriber::EnvFilter;
 
+mod client;
+mod control;
+mod routing;
+mod server;
+
+use routing::{parse_route, Route, RouteTable};
+
 #[derive(Parser, Debug)]
 /// A simple TCP port-forwarding proxy
 ///
 /// Address format:
 /// - IPv4: A.B.C.D:PORT (e.g., 127.0.0.1:5001)
 /// - IPv6: [IPv6]:PORT (e.g., [::1]:9000)
+/// - Hostname: HOST:PORT (e.g., example.com:9000), resolved per connection
 ///
 /// Examples:
 ///   tcp-proxy --listen 127.0.0.1:5001 --to 127.0.0.1:9000
-///   tcp-proxy --listen 0.0.0.0:5000 --to 10.1.1.10:6000 --connect-timeout 2s
+///   tcp-proxy --listen 0.0.0.0:5000 --to example.com:6000 --connect-timeout 2s
 #[command(
     name = "tcp-proxy",
     version,
@@ -26,43 +43,453 @@ use tracing_subscriber::EnvFilter;
     long_about = None
 )]
 struct Cli {
+    /// Reverse-tunnel mode; omit to run the plain forward proxy.
+    #[command(subcommand)]
+    command: Option<Command>,
     /// Local address:port to accept client connections (e.g., 127.0.0.1:5001)
     #[arg(long = "listen", value_name = "ADDR:PORT")]
-    listen: SocketAddr,
-    /// Remote target address:port to forward to (e.g., 127.0.0.1:9000)
-    #[arg(long = "to", value_name = "ADDR:PORT")]
-    to: SocketAddr,
+    listen: Option<SocketAddr>,
+    /// Remote target host:port to forward to (e.g., example.com:9000)
+    #[arg(long = "to", value_name = "HOST:PORT")]
+    to: Option<String>,
+    /// Routing rule `SNI=host:port` or `prefix:BYTES=host:port` (repeatable)
+    #[arg(long = "route", value_name = "MATCH=HOST:PORT", value_parser = parse_route)]
+    route: Vec<Route>,
+    /// Fallback host:port for connections that match no --route
+    #[arg(long = "default-route", value_name = "HOST:PORT")]
+    default_route: Option<String>,
     /// Max time to establish the outbound connection (humantime, e.g., 2s, 500ms)
     #[arg(long = "connect-timeout", default_value = "5s", value_parser = humantime::parse_duration, value_name = "DURATION")]
     connect_timeout: Duration,
+    /// How long a resolved target is cached before re-resolving (humantime, e.g., 30s)
+    #[arg(long = "resolve-interval", default_value = "30s", value_parser = humantime::parse_duration, value_name = "DURATION")]
+    resolve_interval: Duration,
+    /// Delay between staggered Happy Eyeballs connection attempts (humantime, e.g., 250ms)
+    #[arg(long = "happy-eyeballs-delay", default_value = "250ms", value_parser = humantime::parse_duration, value_name = "DURATION")]
+    happy_eyeballs_delay: Duration,
+    /// Grace period to drain in-flight connections on Ctrl+C (humantime, e.g., 30s)
+    #[arg(long = "shutdown-timeout", default_value = "30s", value_parser = humantime::parse_duration, value_name = "DURATION")]
+    shutdown_timeout: Duration,
+    /// Extra outbound connect attempts after the first, with exponential backoff
+    #[arg(long = "connect-retries", default_value_t = 3, value_name = "N")]
+    connect_retries: u32,
+    /// Base delay for the outbound connect backoff (humantime, e.g., 100ms)
+    #[arg(long = "retry-base-delay", default_value = "100ms", value_parser = humantime::parse_duration, value_name = "DURATION")]
+    retry_base_delay: Duration,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Expose a service behind NAT: listen for a control client and a public port.
+    Server {
+        /// Address:port where the tunnel client connects (e.g., 0.0.0.0:7835)
+        #[arg(long = "control", value_name = "ADDR:PORT")]
+        control: SocketAddr,
+        /// Public address:port end users connect to (e.g., 0.0.0.0:8080)
+        #[arg(long = "public", value_name = "ADDR:PORT")]
+        public: SocketAddr,
+        /// Shared secret authenticating the control client
+        #[arg(long = "secret", value_name = "SECRET")]
+        secret: String,
+    },
+    /// Forward public connections from a tunnel server to a local service.
+    Client {
+        /// Reverse-tunnel server control IP:port (e.g., 203.0.113.10:7835); hostnames are not accepted
+        #[arg(long = "server", value_name = "ADDR:PORT")]
+        server: SocketAddr,
+        /// Local service host:port to forward to (e.g., 127.0.0.1:3000)
+        #[arg(long = "local", value_name = "HOST:PORT")]
+        local: String,
+        /// Shared secret authenticating against the server
+        #[arg(long = "secret", value_name = "SECRET")]
+        secret: String,
+    },
+}
+
+/// A forwarding target expressed as a host (name or literal IP) and port, kept
+/// unresolved so that DNS is consulted per connection rather than once at startup.
+#[derive(Clone, Debug)]
+pub(crate) struct Target {
+    host: String,
+    port: u16,
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+/// Split a `host:port` string into its parts, tolerating bracketed IPv6 literals.
+pub(crate) fn parse_target(s: &str) -> anyhow::Result<Target> {
+    let (host, port) = match s.rsplit_once(':') {
+        Some((h, p)) => (h, p),
+        None => bail!("target '{s}' is missing a :port"),
+    };
+    let host = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host);
+    if host.is_empty() {
+        bail!("target '{s}' has an empty host");
+    }
+    let port: u16 = port
+        .parse()
+        .with_context(|| format!("target '{s}' has an invalid port"))?;
+    Ok(Target {
+        host: host.to_string(),
+        port,
+    })
+}
+
+struct CacheEntry {
+    /// Bare resolved IPs; the port is applied at read time so a hostname reused
+    /// on multiple ports doesn't collide on a single cache entry.
+    ips: Vec<IpAddr>,
+    fetched: Instant,
+}
+
+/// Pair each resolved IP with `port`.
+fn with_port(ips: &[IpAddr], port: u16) -> Vec<SocketAddr> {
+    ips.iter().map(|ip| SocketAddr::new(*ip, port)).collect()
+}
+
+/// Resolves `host:port` targets to concrete socket addresses, caching results
+/// for `ttl` so repeated connections don't hammer the system resolver while
+/// still picking up DNS changes once `ttl` elapses. Record TTLs shorter than
+/// `ttl` are not honored by this cache.
+#[derive(Clone)]
+pub(crate) struct TargetResolver {
+    resolver: Arc<TokioAsyncResolver>,
+    cache: Arc<Mutex<HashMap<String, CacheEntry>>>,
+    ttl: Duration,
+}
+
+impl TargetResolver {
+    pub(crate) fn new(ttl: Duration) -> anyhow::Result<Self> {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf()
+            .context("unable to build resolver from system configuration")?;
+        Ok(Self {
+            resolver: Arc::new(resolver),
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
+        })
+    }
+
+    /// Resolve `target` to one or more socket addresses. Literal IPs bypass both
+    /// the resolver and the cache; names are looked up and cached for `ttl`.
+    pub(crate) async fn resolve(&self, target: &Target) -> anyhow::Result<Vec<SocketAddr>> {
+        if let Ok(ip) = target.host.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, target.port)]);
+        }
+
+        {
+            let cache = self.cache.lock().await;
+            if let Some(entry) = cache.get(&target.host) {
+                if entry.fetched.elapsed() < self.ttl {
+                    return Ok(with_port(&entry.ips, target.port));
+                }
+            }
+        }
+
+        let lookup = self
+            .resolver
+            .lookup_ip(target.host.as_str())
+            .await
+            .with_context(|| format!("failed to resolve '{}'", target.host))?;
+        let ips: Vec<IpAddr> = lookup.iter().collect();
+        if ips.is_empty() {
+            bail!("'{}' resolved to no addresses", target.host);
+        }
+        let addrs = with_port(&ips, target.port);
+
+        let mut cache = self.cache.lock().await;
+        cache.insert(
+            target.host.clone(),
+            CacheEntry {
+                ips,
+                fetched: Instant::now(),
+            },
+        );
+        Ok(addrs)
+    }
+}
+
+/// Interleave addresses by family, IPv6 first, per RFC 8305 ordering so that a
+/// broken IPv6 path doesn't delay the first IPv4 attempt by more than one slot.
+fn order_happy_eyeballs(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<SocketAddr>, Vec<SocketAddr>) =
+        addrs.iter().partition(|a| a.is_ipv6());
+    let mut ordered = Vec::with_capacity(addrs.len());
+    let mut v6 = v6.into_iter();
+    let mut v4 = v4.into_iter();
+    loop {
+        let a = v6.next();
+        let b = v4.next();
+        if a.is_none() && b.is_none() {
+            break;
+        }
+        ordered.extend(a);
+        ordered.extend(b);
+    }
+    ordered
+}
+
+/// One timeout-bounded connect attempt, carrying its address so the racer can
+/// report which candidate produced the result.
+async fn attempt_connect(
+    addr: SocketAddr,
+    connect_timeout: Duration,
+) -> (SocketAddr, anyhow::Result<TcpStream>) {
+    let res = match time::timeout(connect_timeout, TcpStream::connect(addr)).await {
+        Ok(Ok(socket)) => Ok(socket),
+        Ok(Err(e)) => Err(anyhow::Error::new(e).context("failed to connect to remote")),
+        Err(_) => Err(anyhow::anyhow!("connect timed out")),
+    };
+    (addr, res)
+}
+
+/// Race connection attempts across resolved addresses using Happy Eyeballs
+/// (RFC 8305): candidates are launched staggered by `delay`, earlier attempts
+/// keep racing, and the first socket to connect wins while the rest are dropped.
+/// With a single address this degenerates to one timeout-bounded connect.
+pub(crate) async fn connect_happy_eyeballs(
+    addrs: Vec<SocketAddr>,
+    connect_timeout: Duration,
+    delay: Duration,
+) -> anyhow::Result<(TcpStream, SocketAddr)> {
+    let ordered = order_happy_eyeballs(&addrs);
+    let mut candidates = ordered.into_iter();
+
+    let first = candidates
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("no addresses to connect to"))?;
+
+    let mut pending = FuturesUnordered::new();
+    info!(remote = %first, "starting connect attempt");
+    pending.push(attempt_connect(first, connect_timeout));
+
+    // `tokio::time::interval` panics on a zero period; clamp so a `0s` stagger
+    // just launches candidates back-to-back.
+    let mut interval = time::interval(delay.max(Duration::from_nanos(1)));
+    // Consume the immediate first tick so the next launch waits a full `delay`.
+    interval.tick().await;
+
+    let mut last_err = None;
+    let mut exhausted = false;
+    loop {
+        if exhausted && pending.is_empty() {
+            return Err(
+                last_err.unwrap_or_else(|| anyhow::anyhow!("no addresses to connect to"))
+            );
+        }
+        tokio::select! {
+            _ = interval.tick(), if !exhausted => {
+                match candidates.next() {
+                    Some(addr) => {
+                        info!(remote = %addr, "starting staggered connect attempt");
+                        pending.push(attempt_connect(addr, connect_timeout));
+                    }
+                    None => exhausted = true,
+                }
+            }
+            Some((addr, res)) = pending.next() => {
+                match res {
+                    Ok(socket) => {
+                        info!(remote = %addr, "connected to remote");
+                        return Ok((socket, addr));
+                    }
+                    Err(e) => {
+                        warn!(remote = %addr, error = %e, "connect attempt failed");
+                        last_err = Some(e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Compute the backoff for retry `attempt` (0-based): `base * 2^attempt` with
+/// +/-50% jitter so concurrent reconnects don't synchronize.
+fn backoff_delay(base: Duration, attempt: u32) -> Duration {
+    let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+    let scaled = base.saturating_mul(factor);
+    let jitter = rand::thread_rng().gen_range(0.5..1.5);
+    scaled.mul_f64(jitter)
+}
+
+/// Connect via Happy Eyeballs, retrying any failure with exponential backoff
+/// up to `retries` additional attempts.
+async fn connect_with_retry(
+    addrs: Vec<SocketAddr>,
+    connect_timeout: Duration,
+    happy_eyeballs_delay: Duration,
+    retries: u32,
+    base_delay: Duration,
+) -> anyhow::Result<(TcpStream, SocketAddr)> {
+    let mut attempt = 0;
+    loop {
+        match connect_happy_eyeballs(addrs.clone(), connect_timeout, happy_eyeballs_delay).await {
+            Ok(ok) => return Ok(ok),
+            Err(e) if attempt < retries => {
+                let backoff = backoff_delay(base_delay, attempt);
+                warn!(attempt = attempt + 1, backoff = ?backoff, "outbound connect failed, retrying: {e}");
+                time::sleep(backoff).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// How a proxied session ended, reported in the closing log line.
+#[derive(Debug, Clone, Copy)]
+enum SessionEnd {
+    ClientEof,
+    RemoteEof,
+    Error,
+}
+
+impl std::fmt::Display for SessionEnd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            SessionEnd::ClientEof => "client EOF",
+            SessionEnd::RemoteEof => "remote EOF",
+            SessionEnd::Error => "error",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Splice two streams, shutting down each write half as soon as the opposing
+/// read half EOFs so a half-closed peer doesn't leak a lingering write task.
+/// Reports which side closed first alongside the byte counts.
+async fn splice(
+    client: &mut TcpStream,
+    remote: &mut TcpStream,
+) -> anyhow::Result<(u64, u64, SessionEnd)> {
+    let (mut cr, mut cw) = client.split();
+    let (mut rr, mut rw) = remote.split();
+
+    let client_to_remote = async {
+        let n = tokio::io::copy(&mut cr, &mut rw).await?;
+        rw.shutdown().await?;
+        Ok::<u64, std::io::Error>(n)
+    };
+    let remote_to_client = async {
+        let n = tokio::io::copy(&mut rr, &mut cw).await?;
+        cw.shutdown().await?;
+        Ok::<u64, std::io::Error>(n)
+    };
+    tokio::pin!(client_to_remote, remote_to_client);
+
+    let (mut c_to_r, mut r_to_c) = (0u64, 0u64);
+    let (mut c_done, mut r_done) = (false, false);
+    let mut first = None;
+    let mut error = None;
+
+    while !(c_done && r_done) {
+        tokio::select! {
+            res = &mut client_to_remote, if !c_done => {
+                c_done = true;
+                match res {
+                    Ok(n) => { c_to_r = n; first.get_or_insert(SessionEnd::ClientEof); }
+                    Err(e) => { first.get_or_insert(SessionEnd::Error); error = Some(e); }
+                }
+            }
+            res = &mut remote_to_client, if !r_done => {
+                r_done = true;
+                match res {
+                    Ok(n) => { r_to_c = n; first.get_or_insert(SessionEnd::RemoteEof); }
+                    Err(e) => { first.get_or_insert(SessionEnd::Error); error = Some(e); }
+                }
+            }
+        }
+    }
+
+    if let Some(e) = error {
+        return Err(anyhow::Error::new(e).context("proxying data"));
+    }
+    Ok((c_to_r, r_to_c, first.unwrap_or(SessionEnd::ClientEof)))
 }
+
+/// Where a freshly accepted connection should be forwarded: a fixed target, or
+/// a routing table that selects the backend from the client's preamble.
+#[derive(Clone)]
+enum Destination {
+    Fixed(Target),
+    Routed(Arc<RouteTable>),
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn handle_connection(
     client_socket: TcpStream,
-    remote_addr: SocketAddr,
+    destination: Destination,
+    resolver: TargetResolver,
     connect_timeout: Duration,
+    happy_eyeballs_delay: Duration,
+    connect_retries: u32,
+    retry_base_delay: Duration,
+    shutdown: CancellationToken,
 ) {
+    #[allow(clippy::too_many_arguments)]
     async fn handle_connection_inner(
         mut client_socket: TcpStream,
-        remote_addr: SocketAddr,
+        destination: Destination,
+        resolver: TargetResolver,
         connect_timeout: Duration,
-    ) -> anyhow::Result<(u64, u64)> {
-        let mut remote_socket = time::timeout(connect_timeout, TcpStream::connect(remote_addr))
-            .await
-            .context("connect timed out")?
-            .context("failed to connect to remote")?;
+        happy_eyeballs_delay: Duration,
+        connect_retries: u32,
+        retry_base_delay: Duration,
+    ) -> anyhow::Result<(u64, u64, SessionEnd)> {
+        let (target, preamble) = match destination {
+            Destination::Fixed(target) => (target, Vec::new()),
+            Destination::Routed(table) => table.route(&mut client_socket).await?,
+        };
 
-        let stats = tokio::io::copy_bidirectional(&mut client_socket, &mut remote_socket)
-            .await
-            .context("proxying data")?;
+        let addrs = resolver.resolve(&target).await?;
+
+        let (mut remote_socket, _chosen) = connect_with_retry(
+            addrs,
+            connect_timeout,
+            happy_eyeballs_delay,
+            connect_retries,
+            retry_base_delay,
+        )
+        .await?;
 
-        Ok(stats)
+        // Replay the sniffed preamble to the backend before splicing the rest.
+        if !preamble.is_empty() {
+            remote_socket
+                .write_all(&preamble)
+                .await
+                .context("replaying routed preamble")?;
+        }
+
+        splice(&mut client_socket, &mut remote_socket).await
     }
 
-    match handle_connection_inner(client_socket, remote_addr, connect_timeout).await {
-        Ok((c_to_r, r_to_c)) => {
+    // Shutdown cancellation covers the whole session, including routing and
+    // the outbound connect, not just the splice.
+    let result = tokio::select! {
+        res = handle_connection_inner(
+            client_socket,
+            destination,
+            resolver,
+            connect_timeout,
+            happy_eyeballs_delay,
+            connect_retries,
+            retry_base_delay,
+        ) => res,
+        _ = shutdown.cancelled() => Err(anyhow::anyhow!("aborted by shutdown")),
+    };
+
+    match result {
+        Ok((c_to_r, r_to_c, end)) => {
             info!(
                 client_to_remote = c_to_r,
                 remote_to_client = r_to_c,
+                ended_by = %end,
                 "closed connection"
             );
         }
@@ -83,22 +510,77 @@ async fn main() -> anyhow::Result<()> {
         .init();
 
     let args = Cli::parse();
-    let listener = TcpListener::bind(args.listen)
+    let connect_timeout = args.connect_timeout;
+    let happy_eyeballs_delay = args.happy_eyeballs_delay;
+
+    match args.command {
+        Some(Command::Server {
+            control,
+            public,
+            secret,
+        }) => return server::run(control, public, secret).await,
+        Some(Command::Client {
+            server,
+            local,
+            secret,
+        }) => {
+            let local = parse_target(&local)?;
+            // Only the client path resolves names, so build the resolver here
+            // rather than unconditionally (server mode never needs DNS).
+            let resolver = TargetResolver::new(args.resolve_interval)?;
+            return client::run(
+                server,
+                local,
+                secret,
+                resolver,
+                connect_timeout,
+                happy_eyeballs_delay,
+            )
+            .await;
+        }
+        None => {}
+    }
+
+    // Forward-proxy mode: --listen is required, along with at least one of
+    // --to / --route. clap can't express this across a subcommand, so enforce
+    // it here rather than with an invalid `required_unless*` constraint.
+    let listen = args
+        .listen
+        .context("--listen is required in forward-proxy mode")?;
+    let destination = if args.route.is_empty() {
+        let to = args
+            .to
+            .context("--to or --route is required in forward-proxy mode")?;
+        Destination::Fixed(parse_target(&to)?)
+    } else {
+        let default = args.default_route.as_deref().map(parse_target).transpose()?;
+        Destination::Routed(Arc::new(RouteTable::new(args.route, default)))
+    };
+
+    let resolver = TargetResolver::new(args.resolve_interval)?;
+
+    let listener = TcpListener::bind(listen)
         .await
         .context("unable to bind listener")?;
 
-    info!(listen = %args.listen, to = %args.to, "Listening (Ctrl+C to stop accepting)");
-
-    let to = args.to;
-    let connect_timeout = args.connect_timeout;
+    match &destination {
+        Destination::Fixed(target) => {
+            info!(listen = %listen, to = %target, "Listening (Ctrl+C to stop accepting)")
+        }
+        Destination::Routed(_) => {
+            info!(listen = %listen, "Listening with routing (Ctrl+C to stop accepting)")
+        }
+    }
 
+    let tracker = TaskTracker::new();
+    let shutdown = CancellationToken::new();
     let mut next_conn_id: u64 = 1;
 
     loop {
         tokio::select! {
             _ = signal::ctrl_c() => {
-                info!("ctrl+c received â€” stopping accepting");
-                return Ok(());
+                info!("ctrl+c received — stopping accepting");
+                break;
             }
             res = listener.accept() => {
                 match res {
@@ -106,12 +588,114 @@ async fn main() -> anyhow::Result<()> {
                         let id = next_conn_id;
                         next_conn_id += 1;
                         info!(id = id, client = %client_addr, "Accepted connection");
-                        let span = tracing::info_span!("conn", id = id, client = %client_addr, remote = %to);
-                        tokio::spawn(handle_connection(socket, to, connect_timeout).instrument(span));
+                        let span = tracing::info_span!("conn", id = id, client = %client_addr);
+                        tracker.spawn(
+                            handle_connection(
+                                socket,
+                                destination.clone(),
+                                resolver.clone(),
+                                connect_timeout,
+                                happy_eyeballs_delay,
+                                args.connect_retries,
+                                args.retry_base_delay,
+                                shutdown.clone(),
+                            )
+                            .instrument(span),
+                        );
                     }
                     Err(e) => warn!(error = %e, "Failed to accept connection"),
                 }
             }
         }
     }
+
+    // Stop accepting and drain in-flight connections, up to --shutdown-timeout.
+    tracker.close();
+    let active = tracker.len();
+    if active > 0 {
+        info!(active, timeout = ?args.shutdown_timeout, "draining connections");
+        tokio::select! {
+            _ = tracker.wait() => info!("all connections drained"),
+            _ = time::sleep(args.shutdown_timeout) => {
+                warn!("shutdown timeout elapsed — aborting remaining connections");
+                shutdown.cancel();
+            }
+            _ = signal::ctrl_c() => {
+                warn!("second ctrl+c — aborting remaining connections");
+                shutdown.cancel();
+            }
+        }
+        tracker.wait().await;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cli() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn parse_target_plain_host() {
+        let t = parse_target("example.com:9000").unwrap();
+        assert_eq!(t.host, "example.com");
+        assert_eq!(t.port, 9000);
+    }
+
+    #[test]
+    fn parse_target_bracketed_ipv6() {
+        let t = parse_target("[::1]:9000").unwrap();
+        assert_eq!(t.host, "::1");
+        assert_eq!(t.port, 9000);
+    }
+
+    #[test]
+    fn parse_target_rejects_empty_host() {
+        assert!(parse_target(":9000").is_err());
+    }
+
+    #[test]
+    fn parse_target_rejects_missing_port() {
+        assert!(parse_target("example.com").is_err());
+    }
+
+    #[test]
+    fn parse_target_rejects_bad_port() {
+        assert!(parse_target("example.com:notaport").is_err());
+        assert!(parse_target("example.com:99999").is_err());
+    }
+
+    #[test]
+    fn order_happy_eyeballs_interleaves_v6_first() {
+        let v4a: SocketAddr = "1.1.1.1:80".parse().unwrap();
+        let v4b: SocketAddr = "2.2.2.2:80".parse().unwrap();
+        let v6a: SocketAddr = "[::1]:80".parse().unwrap();
+        let ordered = order_happy_eyeballs(&[v4a, v4b, v6a]);
+        assert_eq!(ordered, vec![v6a, v4a, v4b]);
+    }
+
+    #[test]
+    fn order_happy_eyeballs_single_family_preserves_order() {
+        let a: SocketAddr = "1.1.1.1:80".parse().unwrap();
+        let b: SocketAddr = "2.2.2.2:80".parse().unwrap();
+        assert_eq!(order_happy_eyeballs(&[a, b]), vec![a, b]);
+    }
+
+    #[test]
+    fn backoff_delay_grows_exponentially() {
+        let base = Duration::from_millis(100);
+        // Jitter is +/-50%, so bound each attempt rather than assert equality.
+        for attempt in 0..4 {
+            let d = backoff_delay(base, attempt);
+            let nominal = base.as_millis() as u64 * (1u64 << attempt);
+            assert!(d.as_millis() as u64 >= nominal / 2);
+            assert!(d.as_millis() as u64 <= nominal * 3 / 2 + 1);
+        }
+    }
 }
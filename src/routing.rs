@@ -0,0 +1,295 @@
+//! Preamble-based routing: pick the backend from the first bytes a client
+//! sends, so one listening port can multiplex many virtual backends.
+//!
+//! TLS connections are routed by the SNI server name parsed out of the
+//! ClientHello; plaintext protocols are routed by a configured leading-byte
+//! pattern or line prefix. The sniffed bytes are never consumed from the
+//! client's perspective: [`RouteTable::route`] returns them so the caller can
+//! replay them to the backend before splicing.
+
+use anyhow::{bail, Context};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+use crate::{parse_target, Target};
+
+/// Maximum number of preamble bytes buffered while sniffing a connection.
+const PREAMBLE_LIMIT: usize = 4096;
+
+/// How a route decides whether it applies to a connection.
+#[derive(Clone, Debug)]
+enum Matcher {
+    /// Exact TLS SNI server name, e.g. `example.com`.
+    Sni(String),
+    /// Literal byte prefix at the start of the stream (plaintext protocols).
+    Prefix(Vec<u8>),
+}
+
+/// A single `<matcher>=<host:port>` routing rule.
+#[derive(Clone, Debug)]
+pub(crate) struct Route {
+    matcher: Matcher,
+    target: Target,
+}
+
+/// Parse a `--route` value of the form `SNI=host:port` or `prefix:BYTES=host:port`.
+pub(crate) fn parse_route(s: &str) -> anyhow::Result<Route> {
+    let (spec, target) = s
+        .rsplit_once('=')
+        .with_context(|| format!("route '{s}' is missing '=host:port'"))?;
+    let target = parse_target(target)?;
+    let matcher = match spec.strip_prefix("prefix:") {
+        Some(prefix) => Matcher::Prefix(prefix.as_bytes().to_vec()),
+        None => Matcher::Sni(spec.to_ascii_lowercase()),
+    };
+    Ok(Route { matcher, target })
+}
+
+/// A set of routes plus an optional fallback for unmatched connections.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RouteTable {
+    routes: Vec<Route>,
+    default: Option<Target>,
+}
+
+impl RouteTable {
+    pub(crate) fn new(routes: Vec<Route>, default: Option<Target>) -> Self {
+        Self { routes, default }
+    }
+
+    /// Sniff the start of `client`, choose a backend, and return it alongside
+    /// the buffered preamble bytes to be replayed to that backend.
+    pub(crate) async fn route(
+        &self,
+        client: &mut TcpStream,
+    ) -> anyhow::Result<(Target, Vec<u8>)> {
+        let preamble = read_preamble(client).await?;
+        let sni = extract_sni(&preamble);
+        for route in &self.routes {
+            let matches = match &route.matcher {
+                Matcher::Sni(name) => sni.as_deref() == Some(name.as_str()),
+                Matcher::Prefix(prefix) => preamble.starts_with(prefix),
+            };
+            if matches {
+                info!(target = %route.target, "routed connection");
+                return Ok((route.target.clone(), preamble));
+            }
+        }
+
+        match &self.default {
+            Some(target) => {
+                info!(target = %target, "routed connection to default backend");
+                Ok((target.clone(), preamble))
+            }
+            None => {
+                warn!(sni = ?sni, "no route matched; closing connection");
+                bail!("no route matched");
+            }
+        }
+    }
+}
+
+/// Buffer the start of the client stream for routing. When the first byte marks
+/// a TLS handshake record, keep reading until the whole record (length in
+/// `buf[3..5]`) is buffered so a ClientHello split across TCP segments is still
+/// parsed; otherwise one read is enough to evaluate prefix matchers. Reads stop
+/// at [`PREAMBLE_LIMIT`] regardless.
+async fn read_preamble(client: &mut TcpStream) -> anyhow::Result<Vec<u8>> {
+    let mut preamble = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+    loop {
+        // Never read past the limit, so every byte taken from the client is
+        // kept for replay.
+        let want = chunk.len().min(PREAMBLE_LIMIT - preamble.len());
+        let n = client
+            .read(&mut chunk[..want])
+            .await
+            .context("reading connection preamble")?;
+        if n == 0 {
+            if preamble.is_empty() {
+                bail!("client closed before sending any data");
+            }
+            break;
+        }
+        preamble.extend_from_slice(&chunk[..n]);
+        if preamble.len() >= PREAMBLE_LIMIT {
+            break;
+        }
+        // For a TLS record, wait for the full first record; otherwise the first
+        // read already carries any leading-byte pattern we route on.
+        if preamble[0] == 0x16 {
+            if preamble.len() < 5 {
+                continue;
+            }
+            let record_len = u16::from_be_bytes([preamble[3], preamble[4]]) as usize;
+            if preamble.len() < 5 + record_len {
+                continue;
+            }
+        }
+        break;
+    }
+    Ok(preamble)
+}
+
+/// Parse the SNI host name out of a buffered TLS ClientHello record, returning
+/// `None` if the bytes are not a ClientHello or carry no server name.
+fn extract_sni(buf: &[u8]) -> Option<String> {
+    // TLS record header: content type (0x16 handshake), version, length.
+    if buf.len() < 5 || buf[0] != 0x16 {
+        return None;
+    }
+    let mut c = Cursor::new(&buf[5..]);
+
+    if c.u8()? != 0x01 {
+        return None; // not a ClientHello
+    }
+    c.skip(3)?; // handshake length
+    c.skip(2)?; // client version
+    c.skip(32)?; // random
+    let session_id = c.u8()? as usize;
+    c.skip(session_id)?;
+    let cipher_suites = c.u16()? as usize;
+    c.skip(cipher_suites)?;
+    let compression = c.u8()? as usize;
+    c.skip(compression)?;
+    let _extensions_len = c.u16()?;
+
+    while let Some(ext_type) = c.u16() {
+        let ext_len = c.u16()? as usize;
+        if ext_type != 0x0000 {
+            c.skip(ext_len)?;
+            continue;
+        }
+        // server_name extension
+        c.skip(2)?; // server name list length
+        if c.u8()? != 0x00 {
+            return None; // not host_name
+        }
+        let name_len = c.u16()? as usize;
+        let name = c.take(name_len)?;
+        return std::str::from_utf8(name).ok().map(|s| s.to_ascii_lowercase());
+    }
+    None
+}
+
+/// Minimal big-endian cursor over a byte slice with bounds-checked reads.
+struct Cursor<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(n)?;
+        let slice = self.buf.get(self.pos..end)?;
+        self.pos = end;
+        Some(slice)
+    }
+
+    fn skip(&mut self, n: usize) -> Option<()> {
+        self.take(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|s| s[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|s| u16::from_be_bytes([s[0], s[1]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a minimal TLS ClientHello record carrying `sni` as the server name.
+    fn client_hello(sni: &str) -> Vec<u8> {
+        let name = sni.as_bytes();
+
+        let mut server_name = Vec::new();
+        server_name.extend_from_slice(&((name.len() + 3) as u16).to_be_bytes());
+        server_name.push(0x00); // host_name
+        server_name.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        server_name.extend_from_slice(name);
+
+        let mut extensions = Vec::new();
+        extensions.extend_from_slice(&0x0000u16.to_be_bytes()); // server_name extension
+        extensions.extend_from_slice(&(server_name.len() as u16).to_be_bytes());
+        extensions.extend_from_slice(&server_name);
+
+        let mut body = Vec::new();
+        body.extend_from_slice(&[0x03, 0x03]); // client version
+        body.extend_from_slice(&[0u8; 32]); // random
+        body.push(0x00); // session id length
+        body.extend_from_slice(&2u16.to_be_bytes()); // cipher suites length
+        body.extend_from_slice(&[0x00, 0x00]); // one cipher suite
+        body.push(0x01); // compression methods length
+        body.push(0x00); // null compression
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut handshake = vec![0x01]; // client_hello
+        let len = body.len();
+        handshake.push((len >> 16) as u8);
+        handshake.push((len >> 8) as u8);
+        handshake.push(len as u8);
+        handshake.extend_from_slice(&body);
+
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+        record.extend_from_slice(&handshake);
+        record
+    }
+
+    #[test]
+    fn extract_sni_from_client_hello() {
+        let hello = client_hello("example.com");
+        assert_eq!(extract_sni(&hello).as_deref(), Some("example.com"));
+    }
+
+    #[test]
+    fn extract_sni_lowercases() {
+        let hello = client_hello("EXAMPLE.COM");
+        assert_eq!(extract_sni(&hello).as_deref(), Some("example.com"));
+    }
+
+    #[test]
+    fn extract_sni_returns_none_on_truncated_hello() {
+        let hello = client_hello("example.com");
+        for cut in 1..hello.len() {
+            // A partial ClientHello must never panic and must not invent a name.
+            assert_eq!(extract_sni(&hello[..cut]), None);
+        }
+    }
+
+    #[test]
+    fn extract_sni_returns_none_on_non_tls() {
+        assert_eq!(extract_sni(b"GET / HTTP/1.1\r\n"), None);
+        assert_eq!(extract_sni(b""), None);
+    }
+
+    #[test]
+    fn parse_route_sni() {
+        let route = parse_route("Example.com=backend:443").unwrap();
+        assert!(matches!(route.matcher, Matcher::Sni(ref s) if s == "example.com"));
+        assert_eq!(route.target.port, 443);
+    }
+
+    #[test]
+    fn parse_route_prefix() {
+        let route = parse_route("prefix:GET =backend:8080").unwrap();
+        assert!(matches!(route.matcher, Matcher::Prefix(ref p) if p == b"GET "));
+        assert_eq!(route.target.port, 8080);
+    }
+
+    #[test]
+    fn parse_route_rejects_missing_target() {
+        assert!(parse_route("example.com").is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! Reverse-tunnel server: exposes a public data port whose connections are
+//! forwarded, over an authenticated control channel, to a client behind NAT.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{oneshot, mpsc, Mutex};
+use tokio::time;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::control::{ClientMessage, DataStream, ServerMessage, Transport};
+use crate::control::verify_challenge;
+
+/// Interval between control-channel heartbeats.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// How long a public connection waits for its matching data stream.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pending public connections keyed by the UUID advertised to the client.
+type Pending = Arc<Mutex<HashMap<Uuid, oneshot::Sender<DataStream>>>>;
+
+/// Run the reverse-tunnel server until an error occurs.
+pub async fn run(
+    control_addr: SocketAddr,
+    public_addr: SocketAddr,
+    secret: String,
+) -> anyhow::Result<()> {
+    let control = TcpListener::bind(control_addr)
+        .await
+        .context("unable to bind control listener")?;
+    info!(control = %control_addr, public = %public_addr, "reverse-tunnel server listening");
+
+    let pending: Pending = Arc::new(Mutex::new(HashMap::new()));
+    let secret = Arc::new(secret);
+
+    loop {
+        let (socket, peer) = control.accept().await.context("accepting control connection")?;
+        let pending = pending.clone();
+        let secret = secret.clone();
+        tokio::spawn(async move {
+            if let Err(err) = accept(socket, peer, public_addr, pending, secret).await {
+                warn!(peer = %peer, "control/data connection error: {err}");
+            }
+        });
+    }
+}
+
+/// Classify a freshly accepted connection as control or data and dispatch it.
+async fn accept(
+    socket: TcpStream,
+    peer: SocketAddr,
+    public_addr: SocketAddr,
+    pending: Pending,
+    secret: Arc<String>,
+) -> anyhow::Result<()> {
+    let mut transport = Transport::new(socket);
+
+    // Every connection is greeted with a challenge; the first client message
+    // tells us whether this is a control connection (Authenticate) or a data
+    // connection claiming a pending public socket (Accept).
+    let challenge = Uuid::new_v4();
+    transport.send(&ServerMessage::Challenge(challenge)).await?;
+
+    match transport.expect::<ClientMessage>().await? {
+        ClientMessage::Authenticate(response) => {
+            if let Err(err) = verify_challenge(&secret, &challenge, &response) {
+                transport
+                    .send(&ServerMessage::Error("authentication failed".into()))
+                    .await
+                    .ok();
+                return Err(err);
+            }
+            info!(peer = %peer, "control connection authenticated");
+            serve_control(transport, public_addr, pending).await
+        }
+        ClientMessage::Accept(uuid) => {
+            let waiter = pending.lock().await.remove(&uuid);
+            match waiter {
+                Some(tx) => {
+                    let _ = tx.send(transport.into_data_stream());
+                    Ok(())
+                }
+                None => {
+                    warn!(uuid = %uuid, "data stream for unknown or expired connection");
+                    Ok(())
+                }
+            }
+        }
+        other => {
+            warn!(peer = %peer, "unexpected first message: {other:?}");
+            Ok(())
+        }
+    }
+}
+
+/// Drive an authenticated control connection: accept public connections and ask
+/// the client to open a data stream for each, while keeping the link alive.
+async fn serve_control(
+    mut transport: Transport,
+    public_addr: SocketAddr,
+    pending: Pending,
+) -> anyhow::Result<()> {
+    let _ = transport.expect::<ClientMessage>().await?; // Hello
+
+    let public = TcpListener::bind(public_addr)
+        .await
+        .context("unable to bind public listener")?;
+    let public_port = public
+        .local_addr()
+        .context("reading public listener address")?
+        .port();
+    transport.send(&ServerMessage::Hello(public_port)).await?;
+
+    let (tx, mut rx) = mpsc::channel::<ServerMessage>(32);
+
+    // Accept public connections and register each as a pending data stream.
+    let acceptor = {
+        let pending = pending.clone();
+        tokio::spawn(async move {
+            loop {
+                let (socket, client) = match public.accept().await {
+                    Ok(pair) => pair,
+                    Err(e) => {
+                        warn!(error = %e, "failed to accept public connection");
+                        continue;
+                    }
+                };
+                let uuid = Uuid::new_v4();
+                info!(uuid = %uuid, client = %client, "public connection accepted");
+                let (data_tx, data_rx) = oneshot::channel::<DataStream>();
+                pending.lock().await.insert(uuid, data_tx);
+                if tx.send(ServerMessage::Connection(uuid)).await.is_err() {
+                    break;
+                }
+                let pending = pending.clone();
+                tokio::spawn(async move {
+                    splice_public(uuid, socket, data_rx, pending).await;
+                });
+            }
+        })
+    };
+
+    let mut heartbeat = time::interval(HEARTBEAT_INTERVAL);
+    heartbeat.tick().await;
+
+    let result = loop {
+        tokio::select! {
+            msg = rx.recv() => match msg {
+                Some(msg) => {
+                    if let Err(err) = transport.send(&msg).await {
+                        break Err(err);
+                    }
+                }
+                None => break Ok(()),
+            },
+            _ = heartbeat.tick() => {
+                if let Err(err) = transport.send(&ServerMessage::Heartbeat).await {
+                    break Err(err);
+                }
+            }
+            incoming = transport.recv::<ClientMessage>() => match incoming {
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    info!("control connection closed by client");
+                    break Ok(());
+                }
+                Err(err) => break Err(err),
+            }
+        }
+    };
+
+    acceptor.abort();
+    result
+}
+
+/// Wait for the client's data stream and splice it to the public socket.
+async fn splice_public(
+    uuid: Uuid,
+    mut public: TcpStream,
+    data_rx: oneshot::Receiver<DataStream>,
+    pending: Pending,
+) {
+    match time::timeout(CONNECTION_TIMEOUT, data_rx).await {
+        Ok(Ok(mut data)) => match tokio::io::copy_bidirectional(&mut public, &mut data).await {
+            Ok((up, down)) => {
+                info!(uuid = %uuid, public_to_client = up, client_to_public = down, "closed tunnel connection");
+            }
+            Err(err) => warn!(uuid = %uuid, "tunnel splice error: {err}"),
+        },
+        _ => {
+            pending.lock().await.remove(&uuid);
+            warn!(uuid = %uuid, "timed out waiting for client data stream");
+        }
+    }
+}